@@ -1,26 +1,198 @@
 use std::sync::Arc;
 
-use crate::{BlockBuildingSnafu, NodeState, Transaction};
+use crate::{NodeState, Transaction};
 use committable::{Commitment, Committable};
 use hotshot_query_service::availability::QueryablePayload;
 use hotshot_types::traits::{states::InstanceState, BlockPayload};
 use hotshot_types::utils::BuilderCommitment;
 use serde::{Deserialize, Serialize};
 use sha2::Digest;
-use snafu::OptionExt;
+use snafu::ResultExt;
 
 pub mod entry;
+pub mod ns_proof;
 pub mod payload;
 pub mod queryable;
 pub mod tables;
 pub mod tx_iterator;
+pub mod version;
+pub mod wal;
 
 use entry::TxTableEntryWord;
 use payload::Payload;
 use tables::NameSpaceTable;
+use version::PayloadVersion;
+use wal::{BlockBuilderWal, WalError};
+
+#[cfg(any(test, feature = "insecure-kzg-srs"))]
+pub use ns_proof::verify_namespace_proof;
+pub use ns_proof::{NsProof, PayloadCommitment};
 
 pub type NsTable = NameSpaceTable<TxTableEntryWord>;
 
+/// Width, in bytes, of a single word in the namespace/tx table layout documented on
+/// [`BlockPayload::from_bytes`].
+///
+/// TODO(746) derive this from `entry::TxTableEntryWord` directly instead of hardcoding it here
+/// (and in [`ns_proof::namespace_byte_range`], which reads this same constant) once that type's
+/// on-wire width is exposed; for now both table readers share this single definition so they
+/// can't drift apart.
+pub(crate) const TABLE_WORD_SIZE: u64 = 4;
+
+#[cfg(any(test, feature = "insecure-kzg-srs"))]
+impl Payload<TxTableEntryWord> {
+    /// Computes the succinct KZG commitment used by [`Self::namespace_proof`], independent of
+    /// [`BlockPayload::builder_commitment`]'s plain SHA-256 digest.
+    ///
+    /// Requires the `insecure-kzg-srs` feature (always on in tests); see [`ns_proof::commit`].
+    pub fn kzg_commitment(&self) -> PayloadCommitment {
+        ns_proof::commit(&self.raw_payload)
+    }
+
+    /// Proves that namespace `ns_id`'s bytes are consistent with [`Self::kzg_commitment`],
+    /// without requiring the verifier to download the rest of the payload. A `ns_id` absent from
+    /// the namespace table degenerates to the same "empty" proof shape, but can never verify:
+    /// [`verify_namespace_proof`] rejects any `ns_id` not found in the trusted namespace table.
+    ///
+    /// Requires the `insecure-kzg-srs` feature (always on in tests); see [`Self::kzg_commitment`].
+    pub fn namespace_proof(&self, ns_id: u32) -> NsProof {
+        let range = ns_proof::namespace_byte_range(&self.ns_table.bytes, ns_id).unwrap_or((0, 0));
+        ns_proof::namespace_proof(&self.raw_payload, ns_id, range)
+    }
+}
+
+impl Payload<TxTableEntryWord> {
+    /// Greedily packs `txs` into a payload, stopping once `max_block_size` (in bytes, counting
+    /// the namespace table and the tx table alongside the raw transaction bytes) would be
+    /// exceeded.
+    ///
+    /// Returns the resulting payload together with any transactions that did not fit, so the
+    /// caller (e.g. a block builder) can requeue them for a future block instead of either
+    /// dropping them or over-filling the block. A transaction that alone exceeds
+    /// `max_block_size` is returned as overflow rather than failing the call; since packing stops
+    /// at the first transaction that doesn't fit, every transaction after it is overflow too.
+    pub fn from_transactions_bounded(
+        txs: impl IntoIterator<Item = Transaction>,
+        max_block_size: u64,
+    ) -> Result<(Self, Vec<Transaction>), <Self as BlockPayload>::Error> {
+        let (included, overflow) = select_transactions(txs, max_block_size);
+        let payload = Self::from_txs(included)?;
+        Ok((payload, overflow))
+    }
+
+    /// Like [`Self::from_transactions_bounded`], but appends every included transaction to `wal`
+    /// (keyed by `view`) as it is folded into the payload, so [`Self::recover_from_wal`] can
+    /// rebuild a byte-identical payload if the builder restarts mid-view.
+    ///
+    /// Safe to call more than once for the same `view` as the builder re-folds newly-arrived
+    /// mempool transactions in: only the tail of `included` beyond what `wal` already has on
+    /// disk for `view` is appended, so repeat calls don't duplicate entries. This relies on
+    /// `included` only ever growing by appending to the same prefix (true as long as `txs` is
+    /// itself only appended to between calls); a call whose `included` diverges from what's
+    /// already on disk for `view` (e.g. `max_block_size` changed between calls, or a reordered
+    /// `txs`) is rejected with [`BuilderError::WalDivergence`] rather than silently logging a
+    /// prefix that doesn't match what [`Self::recover_from_wal`] would later replay.
+    pub fn from_transactions_bounded_with_wal(
+        txs: impl IntoIterator<Item = Transaction>,
+        max_block_size: u64,
+        view: u64,
+        wal: &mut BlockBuilderWal,
+    ) -> Result<(Self, Vec<Transaction>), BuilderError> {
+        let (included, overflow) = select_transactions(txs, max_block_size);
+
+        let already_logged = wal.recover(view);
+        snafu::ensure!(
+            included.len() >= already_logged.len() && included[..already_logged.len()] == already_logged[..],
+            WalDivergenceSnafu {
+                view,
+                prior_len: already_logged.len(),
+            }
+        );
+        for tx in included.iter().skip(already_logged.len()) {
+            wal.append(view, tx).context(WalSnafu)?;
+        }
+        let payload = Self::from_txs(included).context(PayloadSnafu)?;
+        Ok((payload, overflow))
+    }
+
+    /// Rebuilds the payload being assembled for `view` from `wal`'s recovered transactions.
+    ///
+    /// Since transactions are appended to the WAL in the exact order they were folded into the
+    /// payload, replaying them through [`Self::from_txs`] reproduces a byte-identical payload
+    /// (and therefore an identical `builder_commitment`) to the one lost on restart.
+    pub fn recover_from_wal(
+        wal: &BlockBuilderWal,
+        view: u64,
+    ) -> Result<Self, <Self as BlockPayload>::Error> {
+        Self::from_txs(wal.recover(view))
+    }
+}
+
+/// Selects a greedy, order-preserving prefix of `txs` that fits within `max_block_size` bytes
+/// (counting the namespace table and the tx table alongside the raw transaction bytes), and
+/// returns it alongside whatever didn't fit.
+///
+/// A transaction that alone exceeds `max_block_size` is returned as overflow rather than causing
+/// an error; like any other transaction that doesn't fit, it ends the selection.
+///
+/// Stops at the first transaction that doesn't fit rather than skipping over it, so `included`
+/// is always a prefix of `txs` in arrival order; letting later, smaller transactions jump ahead
+/// of an earlier one that didn't fit would silently reorder inclusion relative to arrival.
+fn select_transactions(
+    txs: impl IntoIterator<Item = Transaction>,
+    max_block_size: u64,
+) -> (Vec<Transaction>, Vec<Transaction>) {
+    let mut raw_len: u64 = 0;
+    // One word for the namespace table's entry count, even when it's empty.
+    let mut ns_table_len: u64 = TABLE_WORD_SIZE;
+    let mut seen_namespaces = std::collections::HashSet::new();
+
+    let mut txs = txs.into_iter();
+    let mut included = Vec::new();
+    let mut overflow = Vec::new();
+
+    for tx in &mut txs {
+        // This tx's contribution to `raw_payload`: its bytes plus its tx-table word.
+        let tx_len = tx.payload().len() as u64 + TABLE_WORD_SIZE;
+        // A namespace seen for the first time adds an id/end-offset pair to the ns table.
+        let ns_overhead = if seen_namespaces.contains(&tx.namespace()) {
+            0
+        } else {
+            2 * TABLE_WORD_SIZE
+        };
+
+        if raw_len + tx_len + ns_table_len + ns_overhead > max_block_size {
+            overflow.push(tx);
+            break;
+        }
+
+        raw_len += tx_len;
+        ns_table_len += ns_overhead;
+        seen_namespaces.insert(tx.namespace());
+        included.push(tx);
+    }
+    // Everything after the first rejected transaction is also overflow, regardless of whether it
+    // would itself have fit.
+    overflow.extend(txs);
+
+    (included, overflow)
+}
+
+/// An error building a payload while also driving its [`BlockBuilderWal`].
+#[derive(Debug, snafu::Snafu)]
+pub enum BuilderError {
+    #[snafu(display("{source}"))]
+    Payload { source: crate::Error },
+
+    #[snafu(display("{source}"))]
+    Wal { source: WalError },
+
+    #[snafu(display(
+        "view {view}: included transactions diverge from the {prior_len} already logged to the WAL"
+    ))]
+    WalDivergence { view: u64, prior_len: usize },
+}
+
 impl BlockPayload for Payload<TxTableEntryWord> {
     type Error = crate::Error;
     type Transaction = Transaction;
@@ -48,16 +220,62 @@ impl BlockPayload for Payload<TxTableEntryWord> {
     /// TODO(746) refactor and make pretty "table" code for tx, namespace tables?
     fn from_transactions(
         txs: impl IntoIterator<Item = Self::Transaction>,
-        _state: Arc<dyn InstanceState>,
+        state: Arc<dyn InstanceState>,
     ) -> Result<(Self, Self::Metadata), Self::Error> {
-        let payload = Payload::from_txs(txs)?;
+        let (payload, overflow) =
+            Self::from_transactions_bounded(txs, max_block_size(&state))?;
+        if !overflow.is_empty() {
+            // This trait method's signature (fixed by `BlockPayload`) has no way to hand
+            // overflow back to the caller; callers that need to requeue it should call
+            // `from_transactions_bounded` directly instead.
+            tracing::warn!(
+                dropped = overflow.len(),
+                "from_transactions: transaction(s) did not fit in the block and were dropped"
+            );
+        }
         let ns_table = payload.get_ns_table().clone(); // TODO don't clone ns_table
-        Some((payload, ns_table)).context(BlockBuildingSnafu)
+        Ok((payload, ns_table))
     }
 
+    /// Decodes `encoded_transactions` back into a payload's raw bytes.
+    ///
+    /// `encode`d payloads are prefixed with [`PayloadVersion::with_prefix`]'s `[MAGIC][tag]`, but
+    /// `from_bytes` can't reject bytes it doesn't recognize: its signature (fixed by
+    /// [`BlockPayload`]) is infallible, and it is the path that decodes bytes received from other
+    /// participants, so panicking here would let a malformed or future-versioned payload take a
+    /// node down. If the prefix isn't present or isn't a tag this build recognizes — either
+    /// because it's a payload encoded before this versioning existed (no prefix at all) or a
+    /// newer version we don't understand yet — fall back to decoding the bytes as an untagged
+    /// payload rather than erroring, logging so the mismatch is at least visible.
+    ///
+    /// TODO(746) this can't tell the two "unrecognized prefix" cases apart; a real migration needs
+    /// a fork-activation height (not available in this method's fixed signature) to know
+    /// definitively which wire format old bytes are in. [`version::MAGIC`] makes an accidental
+    /// collision between a legacy payload's leading bytes and a real prefix astronomically
+    /// unlikely rather than eliminating it outright.
     fn from_bytes(encoded_transactions: &[u8], metadata: &Self::Metadata) -> Self {
+        let raw_payload = match PayloadVersion::strip_prefix(encoded_transactions) {
+            Some((version, rest)) => {
+                // `from_tag` (called by `strip_prefix`) only ever returns a variant this build
+                // knows about, and today that's only `CURRENT`; this becomes a real check instead
+                // of a tautology the moment a second `PayloadVersion` variant exists, at which
+                // point `raw_payload`'s layout depends on which one `version` is. See
+                // `version::KNOWN_VARIANT_COUNT` for the matching compile-time guard on the
+                // `encode`/`builder_commitment` side of this same limitation.
+                debug_assert_eq!(version, PayloadVersion::CURRENT);
+                rest.to_vec()
+            }
+            None => {
+                if !encoded_transactions.is_empty() {
+                    tracing::warn!(
+                        "payload has no recognized version prefix; decoding as an untagged (legacy) payload"
+                    );
+                }
+                encoded_transactions.to_vec()
+            }
+        };
         Self {
-            raw_payload: encoded_transactions.to_vec(),
+            raw_payload,
             ns_table: metadata.clone(), // TODO don't clone ns_table
         }
     }
@@ -73,7 +291,7 @@ impl BlockPayload for Payload<TxTableEntryWord> {
     }
 
     fn encode(&self) -> Result<Arc<[u8]>, Self::Error> {
-        Ok(Arc::from(self.raw_payload.clone()))
+        Ok(Arc::from(PayloadVersion::CURRENT.with_prefix(&self.raw_payload)))
     }
 
     fn transaction_commitments(&self, meta: &Self::Metadata) -> Vec<Commitment<Self::Transaction>> {
@@ -83,6 +301,10 @@ impl BlockPayload for Payload<TxTableEntryWord> {
     /// Generate commitment that builders use to sign block options.
     fn builder_commitment(&self, metadata: &Self::Metadata) -> BuilderCommitment {
         let mut digest = sha2::Sha256::new();
+        // Domain-separate by version so commitments can never collide across wire formats.
+        // Assumes `self` was actually built/decoded as `PayloadVersion::CURRENT`; see
+        // version.rs's compile-time guard on that assumption.
+        digest.update([PayloadVersion::CURRENT.tag()]);
         digest.update((self.raw_payload.len() as u64).to_le_bytes());
         digest.update((self.ns_table.bytes.len() as u64).to_le_bytes());
         digest.update((metadata.bytes.len() as u64).to_le_bytes());
@@ -100,6 +322,170 @@ impl BlockPayload for Payload<TxTableEntryWord> {
     }
 }
 
+/// Reads the configured maximum block size out of a type-erased `InstanceState`.
+///
+/// `NodeState` is this crate's only `InstanceState` implementor, so the downcast is expected to
+/// always succeed; `u64::MAX` (effectively unbounded) is used as a conservative fallback so an
+/// unexpected implementor can never wedge block building.
+fn max_block_size(state: &Arc<dyn InstanceState>) -> u64 {
+    state.as_any().downcast_ref::<NodeState>().map(NodeState::max_block_size).unwrap_or_else(|| {
+        tracing::warn!(
+            "InstanceState did not downcast to NodeState; falling back to an unbounded max block size"
+        );
+        u64::MAX
+    })
+}
+
+/// Shared test fixtures for this module and its submodules (`ns_proof`, `wal`, ...), so each
+/// doesn't need its own copy of the reference transaction loader.
+#[cfg(test)]
+pub(crate) mod test_helpers {
+    use crate::Transaction;
+
+    /// Deserializes the reference transaction used across this module's test suites.
+    pub(crate) fn reference_tx() -> Transaction {
+        let json: serde_json::Value =
+            serde_json::from_str(include_str!("../../data/transaction.json")).unwrap();
+        serde_json::from_value(json).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod from_transactions_bounded_tests {
+    use super::*;
+    use test_helpers::reference_tx;
+
+    #[test]
+    fn oversized_tx_is_rejected_not_wedged() {
+        let tx = reference_tx();
+        let (payload, overflow) = Payload::from_transactions_bounded([tx], 1).unwrap();
+        assert!(payload.raw_payload.is_empty());
+        assert_eq!(overflow.len(), 1);
+    }
+
+    #[test]
+    fn txs_that_fit_are_not_held_back() {
+        let tx = reference_tx();
+        let (payload, overflow) = Payload::from_transactions_bounded([tx], u64::MAX).unwrap();
+        assert!(overflow.is_empty());
+        assert!(!payload.raw_payload.is_empty());
+    }
+
+    /// A block packed to its exact byte budget across several transactions in different
+    /// namespaces must keep every transaction that fits (in arrival order) and overflow the rest,
+    /// rather than skipping a too-big transaction to let a later, smaller one jump the queue.
+    #[test]
+    fn fills_to_the_exact_boundary_across_multiple_transactions() {
+        let small = Transaction::new(1.into(), vec![0u8; 8]);
+        let big = Transaction::new(2.into(), vec![0u8; 64]);
+        let also_small = Transaction::new(3.into(), vec![0u8; 8]);
+
+        let exact_fit = select_transactions(
+            [small.clone(), big.clone(), also_small.clone()],
+            u64::MAX,
+        )
+        .0
+        .iter()
+        .map(|tx| tx.payload().len() as u64 + TABLE_WORD_SIZE + 2 * TABLE_WORD_SIZE)
+        .sum::<u64>()
+            + TABLE_WORD_SIZE;
+
+        let (included, overflow) =
+            select_transactions([small.clone(), big.clone(), also_small.clone()], exact_fit);
+        assert_eq!(included, vec![small.clone(), big.clone(), also_small.clone()]);
+        assert!(overflow.is_empty());
+
+        // Shrink the budget to one byte short of what `small` and `big` need together, so `big`
+        // no longer fits. `also_small` fits on its own, but since it arrived after `big`, it must
+        // overflow alongside it rather than being pulled ahead.
+        let also_small_len =
+            also_small.payload().len() as u64 + TABLE_WORD_SIZE + 2 * TABLE_WORD_SIZE;
+        let (included, overflow) = select_transactions(
+            [small.clone(), big.clone(), also_small.clone()],
+            exact_fit - also_small_len - 1,
+        );
+        assert_eq!(included, vec![small]);
+        assert_eq!(overflow, vec![big, also_small]);
+    }
+}
+
+#[cfg(test)]
+mod wire_format_tests {
+    use super::*;
+    use test_helpers::reference_tx;
+
+    /// `builder_commitment` must fold in `PayloadVersion::CURRENT`'s tag byte, so that a change
+    /// in wire format can never collide with a prior version's commitment over the same bytes.
+    #[test]
+    fn builder_commitment_folds_in_the_version_tag() {
+        let tx = reference_tx();
+        let (payload, overflow) = Payload::from_transactions_bounded([tx], u64::MAX).unwrap();
+        assert!(overflow.is_empty());
+        let ns_table = payload.get_ns_table().clone();
+
+        let mut digest = sha2::Sha256::new();
+        digest.update([PayloadVersion::CURRENT.tag()]);
+        digest.update((payload.raw_payload.len() as u64).to_le_bytes());
+        digest.update((payload.ns_table.bytes.len() as u64).to_le_bytes());
+        digest.update((ns_table.bytes.len() as u64).to_le_bytes());
+        digest.update(&payload.raw_payload);
+        digest.update(&payload.ns_table.bytes);
+        digest.update(&ns_table.bytes);
+        let expected = BuilderCommitment::from_raw_digest(digest.finalize());
+
+        let actual = BlockPayload::builder_commitment(&payload, &ns_table);
+        assert_eq!(actual.as_ref(), expected.as_ref());
+
+        // Changing just the tag byte (simulating a future version over identical contents) must
+        // change the commitment.
+        let mut digest_other_tag = sha2::Sha256::new();
+        digest_other_tag.update([PayloadVersion::CURRENT.tag().wrapping_add(1)]);
+        digest_other_tag.update((payload.raw_payload.len() as u64).to_le_bytes());
+        digest_other_tag.update((payload.ns_table.bytes.len() as u64).to_le_bytes());
+        digest_other_tag.update((ns_table.bytes.len() as u64).to_le_bytes());
+        digest_other_tag.update(&payload.raw_payload);
+        digest_other_tag.update(&payload.ns_table.bytes);
+        digest_other_tag.update(&ns_table.bytes);
+        let other_tag_commitment = BuilderCommitment::from_raw_digest(digest_other_tag.finalize());
+        assert_ne!(actual.as_ref(), other_tag_commitment.as_ref());
+    }
+
+    /// `from_bytes` must not panic on a payload with no recognized version tag (e.g. one encoded
+    /// before versioning existed), since it's the path that decodes bytes from other
+    /// participants.
+    #[test]
+    fn from_bytes_does_not_panic_on_an_untagged_legacy_payload() {
+        let legacy_bytes = b"pre-versioning raw payload bytes".to_vec();
+        let ns_table = NsTable::default();
+        let decoded = Payload::from_bytes(&legacy_bytes, &ns_table);
+        assert_eq!(decoded.raw_payload, legacy_bytes);
+    }
+
+    /// A legacy payload whose leading byte happens to equal `PayloadVersion::V0`'s tag byte (`0`)
+    /// must still decode as untagged: detection now requires the full `MAGIC` prefix, not just a
+    /// byte collision with the tag alone.
+    #[test]
+    fn from_bytes_does_not_mistake_a_legacy_leading_zero_byte_for_a_tag() {
+        let legacy_bytes = vec![0u8, 1, 2, 3, 4, 5];
+        let ns_table = NsTable::default();
+        let decoded = Payload::from_bytes(&legacy_bytes, &ns_table);
+        assert_eq!(decoded.raw_payload, legacy_bytes);
+    }
+
+    /// `encode` followed by `from_bytes` must reproduce the original bytes exactly.
+    #[test]
+    fn encode_and_from_bytes_round_trip() {
+        let tx = reference_tx();
+        let (payload, overflow) = Payload::from_transactions_bounded([tx], u64::MAX).unwrap();
+        assert!(overflow.is_empty());
+        let ns_table = payload.get_ns_table().clone();
+
+        let encoded = BlockPayload::encode(&payload).unwrap();
+        let decoded = Payload::from_bytes(&encoded, &ns_table);
+        assert_eq!(decoded.raw_payload, payload.raw_payload);
+    }
+}
+
 #[cfg(test)]
 mod reference {
     //! Reference data types.
@@ -200,4 +586,15 @@ mod reference {
             |tx| tx.commit(),
         );
     }
+
+    // NOTE(chunk0-2 review): a reference vector for `PayloadVersion::V0`'s `builder_commitment`,
+    // pinned to `V0` explicitly so it keeps guarding V0's format even once a later version
+    // becomes the default, belongs here alongside the vectors above. It was attempted twice
+    // (`69859d4`, then again after `e3d29e9`) as an `#[ignore]`d test asserting against a
+    // hand-picked `PINNED` placeholder rather than a digest this test actually produced — an
+    // ignored test against a value nobody computed pins nothing and was dropped both times
+    // (`70278c0`, and again here) rather than ship a fake green checkmark. This repo snapshot has
+    // no Cargo manifest and is missing `payload.rs`/`tables.rs`/`entry.rs`, so the digest can't be
+    // generated by actually running this test in this sandbox; add it for real once a build
+    // exists to run it and capture the logged "V0 builder_commitment bytes" value as `PINNED`.
 }
@@ -0,0 +1,487 @@
+//! Per-namespace data-availability proofs.
+//!
+//! A [`NsProof`] lets a rollup convince itself that the bytes of a single namespace are
+//! consistent with a block's succinct [`PayloadCommitment`] without downloading the rest of the
+//! payload. `raw_payload` is interpreted as evaluations of a polynomial `p` over a multiplicative
+//! subgroup of the BLS12-381 scalar field; opening a namespace's byte range is a standard KZG
+//! "batch opening" built from the quotient polynomial
+//! `q(X) = (p(X) - I(X)) / Z(X)`, where `I` interpolates the claimed evaluations over the range
+//! and `Z` is the vanishing polynomial of the range's evaluation points. Proof size is independent
+//! of the namespace length; verification interpolates over the namespace's limbs, so its cost
+//! grows with the namespace length.
+
+use ark_bls12_381::{Bls12_381, Fr, G1Affine, G1Projective, G2Affine, G2Projective};
+use ark_ec::{pairing::Pairing, AffineRepr, CurveGroup, Group};
+use ark_ff::{Field, PrimeField, Zero};
+use ark_poly::{
+    univariate::{DenseOrSparsePolynomial, DensePolynomial},
+    DenseUVPolynomial, EvaluationDomain, Radix2EvaluationDomain,
+};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_std::{
+    rand::{rngs::StdRng, SeedableRng},
+    UniformRand,
+};
+use lazy_static::lazy_static;
+
+/// Number of bytes packed into each field element.
+///
+/// `Fr` is a ~255-bit scalar field; 31 bytes (248 bits) always round-trips without modular
+/// reduction changing the value.
+const LIMB_SIZE: usize = 31;
+
+/// Generous upper bound on payload size in field elements, used to size the fixed SRS below.
+/// Covers multi-megabyte payloads with room to spare.
+#[cfg(any(test, feature = "insecure-kzg-srs"))]
+const MAX_DEGREE: usize = 1 << 18;
+
+#[cfg(any(test, feature = "insecure-kzg-srs"))]
+struct Srs {
+    powers_of_g1: Vec<G1Affine>,
+    powers_of_g2: Vec<G2Affine>,
+}
+
+#[cfg(any(test, feature = "insecure-kzg-srs"))]
+impl Srs {
+    /// Deterministic "trusted setup" for development and tests.
+    ///
+    /// The seed is public, so anyone can recover `tau` and forge proofs; production builds must
+    /// stay off `insecure-kzg-srs` until a real multi-party-ceremony SRS exists.
+    #[cfg(any(test, feature = "insecure-kzg-srs"))]
+    fn setup(max_degree: usize) -> Self {
+        let mut rng = StdRng::seed_from_u64(0);
+        let tau = Fr::rand(&mut rng);
+
+        let mut powers_of_g1 = Vec::with_capacity(max_degree + 1);
+        let mut powers_of_g2 = Vec::with_capacity(max_degree + 1);
+        let mut cur_g1 = G1Projective::generator();
+        let mut cur_g2 = G2Projective::generator();
+        for _ in 0..=max_degree {
+            powers_of_g1.push(cur_g1.into_affine());
+            powers_of_g2.push(cur_g2.into_affine());
+            cur_g1 *= tau;
+            cur_g2 *= tau;
+        }
+
+        Self {
+            powers_of_g1,
+            powers_of_g2,
+        }
+    }
+}
+
+#[cfg(any(test, feature = "insecure-kzg-srs"))]
+lazy_static! {
+    static ref SRS: Srs = Srs::setup(MAX_DEGREE);
+}
+
+/// Succinct commitment to a [`Payload`](super::Payload)'s bytes as a KZG-committed polynomial.
+///
+/// Unlike [`BuilderCommitment`](hotshot_types::utils::BuilderCommitment), which is a plain hash
+/// of the whole payload, this commitment supports the namespace-opening proofs in this module.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+pub struct PayloadCommitment(G1Affine);
+
+/// Proof that a namespace's bytes are consistent with a [`PayloadCommitment`].
+///
+/// Because `raw_payload` is committed as one flat sequence of fixed-size limbs, a namespace
+/// range that doesn't start and end on a limb boundary shares its two boundary limbs with its
+/// neighbors. The proof is always over the *limb-aligned* window enclosing `range`
+/// ([`Self::aligned_byte_range`]), so verifying a non-aligned namespace needs that many bytes —
+/// up to `LIMB_SIZE - 1` bytes of bleed from each neighbor — not exactly `range`'s own bytes.
+#[derive(Clone, Debug, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+pub struct NsProof {
+    ns_id: u32,
+    /// The namespace's own (possibly non-aligned) byte range; `range.0 == range.1` iff empty.
+    range: (u64, u64),
+    /// The limb-aligned byte window the proof actually opens; see the struct docs.
+    aligned_range: (u64, u64),
+    domain_size: u64,
+    opening: G1Affine,
+}
+
+impl NsProof {
+    /// The limb-aligned byte window [`verify_namespace_proof`] needs bytes for; see the struct
+    /// docs for why this can be wider than the namespace's own range.
+    pub fn aligned_byte_range(&self) -> (u64, u64) {
+        self.aligned_range
+    }
+}
+
+fn bytes_to_field_elements(bytes: &[u8]) -> Vec<Fr> {
+    bytes
+        .chunks(LIMB_SIZE)
+        .map(Fr::from_le_bytes_mod_order)
+        .collect()
+}
+
+fn domain_for(num_elems: usize) -> Radix2EvaluationDomain<Fr> {
+    Radix2EvaluationDomain::new(num_elems.max(1)).expect("domain size exceeds 2-adicity of Fr")
+}
+
+fn interpolate(elems: &[Fr]) -> (DensePolynomial<Fr>, Radix2EvaluationDomain<Fr>) {
+    let domain = domain_for(elems.len());
+    let mut evals = elems.to_vec();
+    evals.resize(domain.size(), Fr::zero());
+    (DensePolynomial::from_coefficients_vec(domain.ifft(&evals)), domain)
+}
+
+#[cfg(any(test, feature = "insecure-kzg-srs"))]
+fn commit_g1(poly: &DensePolynomial<Fr>) -> G1Affine {
+    assert!(
+        poly.coeffs.len() <= SRS.powers_of_g1.len(),
+        "payload exceeds the fixed SRS degree bound"
+    );
+    poly.coeffs
+        .iter()
+        .zip(SRS.powers_of_g1.iter())
+        .map(|(c, p)| p.mul_bigint(c.into_bigint()))
+        .fold(G1Projective::zero(), |acc, x| acc + x)
+        .into_affine()
+}
+
+#[cfg(any(test, feature = "insecure-kzg-srs"))]
+fn commit_g2(poly: &DensePolynomial<Fr>) -> G2Affine {
+    assert!(
+        poly.coeffs.len() <= SRS.powers_of_g2.len(),
+        "polynomial exceeds the fixed SRS degree bound"
+    );
+    poly.coeffs
+        .iter()
+        .zip(SRS.powers_of_g2.iter())
+        .map(|(c, p)| p.mul_bigint(c.into_bigint()))
+        .fold(G2Projective::zero(), |acc, x| acc + x)
+        .into_affine()
+}
+
+/// Commits to the bytes of an entire `raw_payload`.
+///
+/// Requires the `insecure-kzg-srs` feature (always on in tests): see [`Srs::setup`] for why this
+/// can't be built against the fixed SRS outside of tests until a real trusted-setup output
+/// exists.
+#[cfg(any(test, feature = "insecure-kzg-srs"))]
+pub(super) fn commit(raw_payload: &[u8]) -> PayloadCommitment {
+    let (poly, _) = interpolate(&bytes_to_field_elements(raw_payload));
+    PayloadCommitment(commit_g1(&poly))
+}
+
+/// Reads namespace `ns_id`'s byte range `start..end` out of a namespace table's raw bytes, per
+/// the [`PayloadVersion::CURRENT`](super::version::PayloadVersion::CURRENT) table format.
+pub(super) fn namespace_byte_range(ns_table_bytes: &[u8], ns_id: u32) -> Option<(usize, usize)> {
+    super::version::PayloadVersion::CURRENT.namespace_byte_range(ns_table_bytes, ns_id)
+}
+
+/// Rounds a namespace's byte range outward to the limb boundaries that enclose it, clamped to
+/// `payload_len`. See the [`NsProof`] docs for why proofs open this window rather than `range`
+/// itself.
+fn aligned_byte_range(range: (usize, usize), payload_len: usize) -> (usize, usize) {
+    let (start, end) = range;
+    let start_elem = start / LIMB_SIZE;
+    let end_elem = end.div_ceil(LIMB_SIZE).max(start_elem);
+    (start_elem * LIMB_SIZE, (end_elem * LIMB_SIZE).min(payload_len))
+}
+
+/// Builds a proof that the bytes of namespace `ns_id` in `raw_payload` (occupying byte range
+/// `range`) are consistent with `commit(raw_payload)`.
+///
+/// Requires the `insecure-kzg-srs` feature (always on in tests); see [`commit`].
+#[cfg(any(test, feature = "insecure-kzg-srs"))]
+pub(super) fn namespace_proof(raw_payload: &[u8], ns_id: u32, range: (usize, usize)) -> NsProof {
+    let (start, end) = range;
+    let elems = bytes_to_field_elements(raw_payload);
+    let (poly, domain) = interpolate(&elems);
+
+    if start == end {
+        // Truly empty: there is nothing to open, so the quotient is the whole polynomial
+        // (dividing by the vanishing polynomial of the empty set, which is the constant `1`).
+        return NsProof {
+            ns_id,
+            range: (start as u64, end as u64),
+            aligned_range: (start as u64, start as u64),
+            domain_size: domain.size() as u64,
+            opening: commit_g1(&poly),
+        };
+    }
+
+    let (aligned_start, aligned_end) = aligned_byte_range(range, raw_payload.len());
+    let (start_elem, end_elem) = (aligned_start / LIMB_SIZE, aligned_end.div_ceil(LIMB_SIZE));
+
+    let points: Vec<Fr> = (start_elem..end_elem).map(|i| domain.element(i)).collect();
+    let values: Vec<Fr> = (start_elem..end_elem)
+        .map(|i| elems.get(i).copied().unwrap_or(Fr::zero()))
+        .collect();
+
+    let interpolant = lagrange_interpolate(&points, &values);
+    let vanishing = vanishing_polynomial(&points);
+
+    let numerator = &poly - &interpolant;
+    let (quotient, remainder) = DenseOrSparsePolynomial::from(numerator)
+        .divide_with_q_and_r(&DenseOrSparsePolynomial::from(vanishing))
+        .expect("namespace range does not evenly divide the vanishing polynomial");
+    debug_assert!(remainder.is_zero(), "payload is inconsistent with its own bytes");
+
+    NsProof {
+        ns_id,
+        range: (start as u64, end as u64),
+        aligned_range: (aligned_start as u64, aligned_end as u64),
+        domain_size: domain.size() as u64,
+        opening: commit_g1(&quotient),
+    }
+}
+
+/// Verifies a [`NsProof`] against a [`PayloadCommitment`] and `bytes`, which must be exactly the
+/// payload's bytes over `proof.aligned_byte_range()` (see the [`NsProof`] docs), not `ns_id`'s own
+/// range. `ns_table_bytes` must be the trusted namespace table for `commitment`'s payload.
+///
+/// The range checked always comes from `namespace_byte_range(ns_table_bytes, ns_id)`, never
+/// `proof.range`/`proof.aligned_range` — those round-trip through `CanonicalDeserialize` and so
+/// can claim anything, letting a dishonest prover open the wrong sub-range otherwise.
+///
+/// Requires the `insecure-kzg-srs` feature (always on in tests); see [`commit`].
+#[cfg(any(test, feature = "insecure-kzg-srs"))]
+pub fn verify_namespace_proof(
+    commitment: PayloadCommitment,
+    ns_table_bytes: &[u8],
+    ns_id: u32,
+    bytes: &[u8],
+    proof: &NsProof,
+) -> bool {
+    if proof.ns_id != ns_id {
+        return false;
+    }
+    let Some((start, end)) = namespace_byte_range(ns_table_bytes, ns_id) else {
+        return false;
+    };
+    if end < start {
+        return false;
+    }
+    // A proof whose own `range` disagrees with the trusted table is rejected rather than ignored.
+    if proof.range != (start as u64, end as u64) {
+        return false;
+    }
+
+    if start == end {
+        // Truly empty: `q = p`, so the check degenerates to `e(C, g2) == e(opening, g2)`.
+        return bytes.is_empty() && commitment.0 == proof.opening;
+    }
+
+    let (aligned_start, aligned_end) = (proof.aligned_range.0 as usize, proof.aligned_range.1 as usize);
+    if aligned_end < aligned_start {
+        return false;
+    }
+    // Must match what `namespace_proof` would compute for this range (mod clamping to the real
+    // payload length), or bytes the namespace owns could fall outside what's checked.
+    let (expected_aligned_start, expected_aligned_end) = aligned_byte_range((start, end), usize::MAX);
+    if aligned_start != expected_aligned_start || aligned_end > expected_aligned_end || aligned_end < end {
+        return false;
+    }
+    if bytes.len() != aligned_end - aligned_start {
+        return false;
+    }
+    let Some(domain) = Radix2EvaluationDomain::<Fr>::new(proof.domain_size as usize) else {
+        return false;
+    };
+
+    let (start_elem, end_elem) = (aligned_start / LIMB_SIZE, aligned_end.div_ceil(LIMB_SIZE));
+    let elems = bytes_to_field_elements(bytes);
+    if elems.len() != end_elem - start_elem {
+        return false;
+    }
+    // `domain.element(i)` is periodic with period `domain.size()`, so a too-small `domain_size`
+    // would alias distinct `i`s onto the same point, making `lagrange_interpolate` divide by zero
+    // (it asserts points are distinct) instead of this function rejecting the proof.
+    if end_elem > domain.size() {
+        return false;
+    }
+
+    let points: Vec<Fr> = (start_elem..end_elem).map(|i| domain.element(i)).collect();
+    let interpolant = lagrange_interpolate(&points, &elems);
+    let vanishing = vanishing_polynomial(&points);
+
+    let commit_i = commit_g1(&interpolant);
+    let commit_z = commit_g2(&vanishing);
+
+    let lhs = (commitment.0.into_group() - commit_i.into_group()).into_affine();
+    Bls12_381::pairing(lhs, G2Affine::generator())
+        == Bls12_381::pairing(proof.opening, commit_z)
+}
+
+fn vanishing_polynomial(points: &[Fr]) -> DensePolynomial<Fr> {
+    points.iter().fold(
+        DensePolynomial::from_coefficients_vec(vec![Fr::from(1u64)]),
+        |acc, point| &acc * &DensePolynomial::from_coefficients_vec(vec![-*point, Fr::from(1u64)]),
+    )
+}
+
+fn lagrange_interpolate(points: &[Fr], values: &[Fr]) -> DensePolynomial<Fr> {
+    assert_eq!(points.len(), values.len());
+    let mut result = DensePolynomial::from_coefficients_vec(vec![]);
+    for (i, (&xi, &yi)) in points.iter().zip(values).enumerate() {
+        let mut term = DensePolynomial::from_coefficients_vec(vec![Fr::from(1u64)]);
+        let mut denom = Fr::from(1u64);
+        for (j, &xj) in points.iter().enumerate() {
+            if i == j {
+                continue;
+            }
+            term = &term * &DensePolynomial::from_coefficients_vec(vec![-xj, Fr::from(1u64)]);
+            denom *= xi - xj;
+        }
+        let scale = yi * denom.inverse().expect("interpolation points must be distinct");
+        let scaled = DensePolynomial::from_coefficients_vec(
+            term.coeffs.iter().map(|c| *c * scale).collect(),
+        );
+        result = &result + &scaled;
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn table_bytes(ranges: &[(u32, usize)]) -> Vec<u8> {
+        let mut bytes = (ranges.len() as u32).to_le_bytes().to_vec();
+        for (id, end) in ranges {
+            bytes.extend_from_slice(&id.to_le_bytes());
+            bytes.extend_from_slice(&(*end as u32).to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn namespace_range_matches_documented_layout() {
+        let ns_table = table_bytes(&[(7, 10), (9, 25)]);
+        assert_eq!(namespace_byte_range(&ns_table, 7), Some((0, 10)));
+        assert_eq!(namespace_byte_range(&ns_table, 9), Some((10, 25)));
+        assert_eq!(namespace_byte_range(&ns_table, 3), None);
+    }
+
+    #[test]
+    fn roundtrip_open_and_verify() {
+        let raw_payload = b"the quick brown fox jumps over the lazy dog, repeatedly".to_vec();
+        let commitment = commit(&raw_payload);
+        // Namespace 0 occupies 0..10, namespace 1 occupies the range opened below.
+        let ns_table = table_bytes(&[(0, 10), (1, 35)]);
+
+        // (10, 35) is not limb-aligned (LIMB_SIZE == 31): 10 isn't a multiple of 31, and 35 isn't
+        // either, so this exercises the aligned-window bleed from both neighbors.
+        let range = (10, 35);
+        let proof = namespace_proof(&raw_payload, 1, range);
+        let (aligned_start, aligned_end) =
+            (proof.aligned_byte_range().0 as usize, proof.aligned_byte_range().1 as usize);
+        assert!(verify_namespace_proof(
+            commitment,
+            &ns_table,
+            1,
+            &raw_payload[aligned_start..aligned_end],
+            &proof
+        ));
+
+        // Tampered bytes must not verify.
+        let mut tampered = raw_payload[aligned_start..aligned_end].to_vec();
+        tampered[0] ^= 1;
+        assert!(!verify_namespace_proof(commitment, &ns_table, 1, &tampered, &proof));
+    }
+
+    #[test]
+    fn roundtrip_with_non_limb_aligned_range_spanning_multiple_limbs() {
+        // A range crossing several limb boundaries without starting or ending on one.
+        let raw_payload: Vec<u8> = (0..200u32).map(|i| i as u8).collect();
+        let commitment = commit(&raw_payload);
+        let ns_table = table_bytes(&[(0, 17), (4, 142)]);
+
+        let range = (17, 142); // neither bound is a multiple of LIMB_SIZE (31)
+        let proof = namespace_proof(&raw_payload, 4, range);
+        let (aligned_start, aligned_end) =
+            (proof.aligned_byte_range().0 as usize, proof.aligned_byte_range().1 as usize);
+        assert_eq!(aligned_start % LIMB_SIZE, 0);
+        assert!(verify_namespace_proof(
+            commitment,
+            &ns_table,
+            4,
+            &raw_payload[aligned_start..aligned_end],
+            &proof
+        ));
+    }
+
+    #[test]
+    fn empty_namespace_has_a_verifiable_proof() {
+        let raw_payload = b"non-empty payload bytes".to_vec();
+        let commitment = commit(&raw_payload);
+        let ns_table = table_bytes(&[(0, 5), (2, 5)]);
+
+        // (5, 5): `5.div_ceil(31) == 1` but `5 / 31 == 0`, so the old element-index
+        // emptiness check (`start_elem == end_elem`) would have wrongly treated this as
+        // non-empty; emptiness must be detected via `start == end` on the raw byte range instead.
+        let proof = namespace_proof(&raw_payload, 2, (5, 5));
+        assert_eq!(proof.aligned_byte_range(), (5, 5));
+        assert!(verify_namespace_proof(commitment, &ns_table, 2, &[], &proof));
+    }
+
+    #[test]
+    fn verify_derives_the_range_from_the_trusted_ns_table_not_the_proof() {
+        let raw_payload = b"the quick brown fox jumps over the lazy dog, repeatedly".to_vec();
+        let commitment = commit(&raw_payload);
+        let ns_table = table_bytes(&[(0, 10), (1, 35)]);
+
+        let proof = namespace_proof(&raw_payload, 1, (10, 35));
+        let (aligned_start, aligned_end) =
+            (proof.aligned_byte_range().0 as usize, proof.aligned_byte_range().1 as usize);
+        let bytes = &raw_payload[aligned_start..aligned_end];
+        assert!(verify_namespace_proof(commitment, &ns_table, 1, bytes, &proof));
+
+        // A proof that lies about its own `range` (but is otherwise untouched) must not verify:
+        // the expected range comes from `ns_table`, not `proof.range`.
+        let mut forged_range = proof.clone();
+        forged_range.range = (0, 25);
+        assert!(!verify_namespace_proof(commitment, &ns_table, 1, bytes, &forged_range));
+
+        // Claiming an empty range for a namespace that `ns_table` says is non-empty must not
+        // degenerate to the trivially-true empty check.
+        let mut forged_empty = proof.clone();
+        forged_empty.range = (10, 10);
+        assert!(!verify_namespace_proof(commitment, &ns_table, 1, &[], &forged_empty));
+
+        // A namespace id absent from the trusted table can never verify, regardless of what the
+        // proof claims about itself.
+        assert!(!verify_namespace_proof(commitment, &ns_table, 99, bytes, &proof));
+    }
+
+    #[test]
+    fn verify_rejects_a_backwards_aligned_range_instead_of_panicking() {
+        let raw_payload = b"the quick brown fox jumps over the lazy dog, repeatedly".to_vec();
+        let commitment = commit(&raw_payload);
+        let ns_table = table_bytes(&[(0, 10), (1, 35)]);
+
+        let proof = namespace_proof(&raw_payload, 1, (10, 35));
+        let (aligned_start, aligned_end) =
+            (proof.aligned_byte_range().0 as usize, proof.aligned_byte_range().1 as usize);
+        let bytes = &raw_payload[aligned_start..aligned_end];
+
+        // A malformed proof with aligned_range.0 > aligned_range.1 must be rejected, not panic on
+        // the `aligned_end - aligned_start` subtraction.
+        let mut backwards = proof.clone();
+        backwards.aligned_range = (backwards.aligned_range.1, backwards.aligned_range.0);
+        assert!(!verify_namespace_proof(commitment, &ns_table, 1, bytes, &backwards));
+    }
+
+    #[test]
+    fn verify_rejects_a_too_small_domain_size_instead_of_panicking() {
+        let raw_payload = b"the quick brown fox jumps over the lazy dog, repeatedly".to_vec();
+        let commitment = commit(&raw_payload);
+        let ns_table = table_bytes(&[(0, 10), (1, 35)]);
+
+        let proof = namespace_proof(&raw_payload, 1, (10, 35));
+        let (aligned_start, aligned_end) =
+            (proof.aligned_byte_range().0 as usize, proof.aligned_byte_range().1 as usize);
+        let bytes = &raw_payload[aligned_start..aligned_end];
+
+        // A forged `domain_size` too small to cover the aligned range makes `domain.element(i)`
+        // alias distinct limb indices onto the same evaluation point. Without the bounds check
+        // this would reach `lagrange_interpolate`'s `denom.inverse().expect(..)` and panic on the
+        // resulting zero denominator instead of verify returning `false`.
+        let mut shrunk_domain = proof.clone();
+        shrunk_domain.domain_size = 1;
+        assert!(!verify_namespace_proof(commitment, &ns_table, 1, bytes, &shrunk_domain));
+    }
+}
@@ -0,0 +1,176 @@
+//! `Payload` wire-format versioning.
+//!
+//! BLOCKED, NOT DONE (see #chunk0-2 review): the request is for `Payload` itself to become an
+//! enum whose variants share a table-parsing trait, mirroring `Base`/`Altair`/`Merge`/`Capella`,
+//! with `encode`/`from_bytes` dispatching on the discriminant and `builder_commitment` folding in
+//! the instance's actual version. None of that is true yet. `Payload` (defined in `payload.rs`,
+//! a file this diff cannot touch) is still the same plain struct it always was, and that
+//! restructuring has to happen there before a second [`PayloadVersion`] variant can exist at all.
+//! What this module ships instead is the wire-tagging half of that design ([`MAGIC`]-plus-tag
+//! framing, stripped/prepended by [`BlockPayload::from_bytes`]/[`BlockPayload::encode`]) and a
+//! [`TableFormat`] trait with a single `V0` impl — both real, both load-bearing, neither the thing
+//! the request asked for. Do not merge this as closing out the request; it stays open pending the
+//! `payload.rs` restructuring (see `KNOWN_VARIANT_COUNT` below for the compile-time trip-wire that
+//! blocks a second variant until that happens) and pending a real build to generate the pinned
+//! `V0` `builder_commitment` reference vector the request also requires (`block.rs`'s `reference`
+//! test module).
+
+use serde::{Deserialize, Serialize};
+
+/// Per-version namespace/tx table layout.
+///
+/// Implemented once per [`PayloadVersion`] variant; [`PayloadVersion::namespace_byte_range`]
+/// dispatches to the right impl so callers don't need to match on the version themselves.
+pub trait TableFormat {
+    /// Reads namespace `ns_id`'s byte range `start..end` out of a namespace table's raw bytes.
+    fn namespace_byte_range(ns_table_bytes: &[u8], ns_id: u32) -> Option<(usize, usize)>;
+}
+
+/// The original table layout: explicit final table entry, `TxTableEntry` words.
+///
+/// Word `0` is the entry count, word `2j-1`/`2j` are namespace `j`'s id/end offset, and the first
+/// namespace's start is implicitly `0`.
+pub struct V0;
+
+impl TableFormat for V0 {
+    fn namespace_byte_range(ns_table_bytes: &[u8], ns_id: u32) -> Option<(usize, usize)> {
+        let word_size = super::TABLE_WORD_SIZE as usize;
+
+        let read_word = |idx: usize| -> Option<usize> {
+            let start = idx * word_size;
+            let word = ns_table_bytes.get(start..start + word_size)?;
+            Some(u32::from_le_bytes(word.try_into().unwrap()) as usize)
+        };
+
+        let num_entries = read_word(0)?;
+        let mut start = 0;
+        for j in 1..=num_entries {
+            let id = read_word(2 * j - 1)? as u32;
+            let end = read_word(2 * j)?;
+            if id == ns_id {
+                return Some((start, end));
+            }
+            start = end;
+        }
+        None
+    }
+}
+
+/// Magic prefix marking a [`PayloadVersion`]-tagged payload.
+///
+/// A single reserved tag byte can't be proven safe against a legacy (pre-versioning)
+/// `raw_payload`: its leading bytes are just the first transaction's own content/tx-table words,
+/// which can plausibly be any byte value, including `0`. Prefixing with this 4-byte magic instead
+/// of relying on the tag byte alone shrinks an accidental collision from 1-in-256 to
+/// 1-in-2^32 — not a proof of impossibility, but enough that it's no longer a practical concern
+/// pending the fork-activation-height gating described on [`super::Payload::from_bytes`].
+pub const MAGIC: [u8; 4] = [0xE5, 0x50, 0x56, 0x00];
+
+/// Discriminant identifying a [`Payload`](super::Payload)'s on-wire namespace/tx table format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum PayloadVersion {
+    /// The original layout: explicit final table entry, `TxTableEntry` words.
+    V0 = 0,
+}
+
+impl PayloadVersion {
+    /// The version written by this build of the sequencer.
+    pub const CURRENT: Self = Self::V0;
+
+    /// Looks up the version identified by an on-wire tag byte.
+    pub fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(Self::V0),
+            _ => None,
+        }
+    }
+
+    /// The on-wire tag byte for this version.
+    pub fn tag(self) -> u8 {
+        self as u8
+    }
+
+    /// Strips this version's `[MAGIC][tag]` wire prefix off the front of `bytes`, returning the
+    /// version and the remaining bytes. `None` if `bytes` doesn't start with [`MAGIC`] (e.g. a
+    /// legacy untagged payload, or one too short to hold the prefix) or the byte after `MAGIC`
+    /// isn't a recognized tag.
+    pub fn strip_prefix(bytes: &[u8]) -> Option<(Self, &[u8])> {
+        let rest = bytes.strip_prefix(MAGIC.as_slice())?;
+        let (&tag, rest) = rest.split_first()?;
+        Some((Self::from_tag(tag)?, rest))
+    }
+
+    /// Prepends this version's `[MAGIC][tag]` wire prefix to `bytes`.
+    pub fn with_prefix(self, bytes: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(MAGIC.len() + 1 + bytes.len());
+        out.extend_from_slice(&MAGIC);
+        out.push(self.tag());
+        out.extend_from_slice(bytes);
+        out
+    }
+
+    /// Reads namespace `ns_id`'s byte range out of a namespace table's raw bytes, per this
+    /// version's [`TableFormat`] impl.
+    pub fn namespace_byte_range(self, ns_table_bytes: &[u8], ns_id: u32) -> Option<(usize, usize)> {
+        match self {
+            Self::V0 => V0::namespace_byte_range(ns_table_bytes, ns_id),
+        }
+    }
+}
+
+/// Compile-time guard: [`BlockPayload::encode`](super::BlockPayload::encode) and
+/// [`BlockPayload::builder_commitment`](super::BlockPayload::builder_commitment) unconditionally
+/// tag/digest with [`PayloadVersion::CURRENT`] rather than a version the `Payload` instance itself
+/// remembers being parsed/built with — sound only while `CURRENT` is the *only* variant, since a
+/// `Payload` decoded from an older version's bytes would otherwise get silently re-tagged and
+/// re-committed as `CURRENT`. `Payload` doesn't carry its own version (that struct lives in
+/// `payload.rs`, outside this module), so this can't be fixed at the type level yet. Bump
+/// `KNOWN_VARIANT_COUNT` when adding a variant; the assertion failing is a deliberate stop sign to
+/// make `Payload` carry its actual version before `encode`/`builder_commitment` can safely stop
+/// assuming `CURRENT`.
+const KNOWN_VARIANT_COUNT: usize = 1;
+const _: () = assert!(
+    KNOWN_VARIANT_COUNT == 1,
+    "a second PayloadVersion variant exists; Payload must carry its own version before encode/\
+     builder_commitment can stop assuming PayloadVersion::CURRENT (see this constant's doc)"
+);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tag_roundtrips() {
+        assert_eq!(PayloadVersion::from_tag(PayloadVersion::V0.tag()), Some(PayloadVersion::V0));
+    }
+
+    #[test]
+    fn unknown_tag_is_rejected() {
+        assert_eq!(PayloadVersion::from_tag(0xff), None);
+    }
+
+    #[test]
+    fn namespace_byte_range_matches_the_documented_layout() {
+        let mut bytes = 1u32.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&7u32.to_le_bytes());
+        bytes.extend_from_slice(&10u32.to_le_bytes());
+        assert_eq!(PayloadVersion::CURRENT.namespace_byte_range(&bytes, 7), Some((0, 10)));
+        assert_eq!(PayloadVersion::CURRENT.namespace_byte_range(&bytes, 3), None);
+    }
+
+    #[test]
+    fn prefix_roundtrips() {
+        let bytes = b"some raw payload bytes".to_vec();
+        let prefixed = PayloadVersion::CURRENT.with_prefix(&bytes);
+        assert_eq!(PayloadVersion::strip_prefix(&prefixed), Some((PayloadVersion::CURRENT, &bytes[..])));
+    }
+
+    #[test]
+    fn strip_prefix_rejects_bytes_without_the_magic() {
+        // A legacy payload whose first byte happens to equal the tag byte alone (`0`) must not
+        // be mistaken for a tagged one now that tagging requires the full `MAGIC` prefix too.
+        let legacy = vec![0u8, 1, 2, 3];
+        assert_eq!(PayloadVersion::strip_prefix(&legacy), None);
+    }
+}